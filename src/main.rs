@@ -1,15 +1,22 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, Read};
 use std::process::exit;
 
 use anyhow::{bail, Result};
 use clap::Parser;
 
+mod parser;
+use parser::{RangeItem, Segment};
+
 /// sexpand
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Args {
-    /// SLURM-based hostname pattern to expand
+    /// SLURM-based hostname pattern to expand. Required unless --fold is
+    /// set, in which case a comma/whitespace-separated host list is read
+    /// from stdin when omitted.
     #[clap(value_name = "PATTERN")]
-    pattern: String,
+    pattern: Option<String>,
 
     /// Expression using '{}' to expand the hostnames into
     #[clap(value_name = "EXPRESSION", default_value = "{}")]
@@ -18,6 +25,25 @@ struct Args {
     /// Separator of final expanded hostnames
     #[clap(value_name = "SEPARATOR", default_value = ",")]
     separator: String,
+
+    /// Fold a hostname list into a compact SLURM-style pattern instead of
+    /// expanding. Reads PATTERN as a comma/whitespace-separated host list,
+    /// or from stdin if PATTERN is omitted.
+    #[clap(long)]
+    fold: bool,
+
+    /// Additional pattern(s) to union with PATTERN before expansion. May
+    /// be given more than once.
+    #[clap(short = 'p', long = "pattern", value_name = "PATTERN")]
+    extra_patterns: Vec<String>,
+
+    /// Exclude hosts matching this pattern from the result
+    #[clap(long, value_name = "PATTERN")]
+    exclude: Option<String>,
+
+    /// Intersect the result with hosts matching this pattern
+    #[clap(long, value_name = "PATTERN")]
+    intersect: Option<String>,
 }
 
 fn pad_number(num: i32, pad: i32) -> String {
@@ -59,6 +85,45 @@ fn get_host_range(prefix: &str, start_num: &str, end_num: &str) -> Result<Vec<St
     Ok(hostnames)
 }
 
+/// Expand a single `RangeItem` (a bare number or a `start-end` range) into
+/// its list of zero-padded number strings.
+fn expand_range_item(item: &RangeItem) -> Result<Vec<String>> {
+    match item {
+        RangeItem::Single(n) => Ok(vec![n.clone()]),
+        RangeItem::Range(start, end) => get_host_range("", start, end),
+    }
+}
+
+/// Expand an ordered list of segments (as produced by `parser::parse_pattern`)
+/// into the Cartesian product of its range-sets, with literals interleaved
+/// in order and preserved on every result.
+fn expand_segments(segments: &[Segment]) -> Result<Vec<String>> {
+    let mut results = vec![String::new()];
+    for segment in segments {
+        match segment {
+            Segment::Literal(s) => {
+                for result in results.iter_mut() {
+                    result.push_str(s);
+                }
+            }
+            Segment::RangeSet(items) => {
+                let mut values = Vec::new();
+                for item in items {
+                    values.extend(expand_range_item(item)?);
+                }
+                let mut next = Vec::with_capacity(results.len() * values.len());
+                for result in &results {
+                    for value in &values {
+                        next.push(result.clone() + value);
+                    }
+                }
+                results = next;
+            }
+        }
+    }
+    Ok(results)
+}
+
 /// Expand the SLURM-based hostname pattern into a list of hostnames
 /// # Arguments
 /// * `pattern` - SLURM-based hostname pattern
@@ -70,94 +135,307 @@ fn get_host_range(prefix: &str, start_num: &str, end_num: &str) -> Result<Vec<St
 /// ==
 /// n01,n02,n03,n05,n06,n07
 ///
+/// Patterns may contain more than one bracket group and literal text
+/// between, before, or after them; the result is the Cartesian product of
+/// the groups with the literals preserved in place, e.g.
+/// `rack[1-2]node[0-1]` yields `rack1node0, rack1node1, rack2node0, rack2node1`.
+/// Parsing is delegated to the [`parser`] module's grammar, which reports
+/// malformed input (unbalanced or nested brackets, a dangling `-`) with
+/// the byte offset where it broke down.
 fn expand_hostnames(pattern: &str) -> Result<Vec<String>> {
-    // keep track of brackets and expand commas
+    let elements = parser::parse_pattern(pattern)?;
     let mut hostnames: Vec<String> = Vec::new();
-    let mut queue: Vec<String> = Vec::new();
-    let mut nest_counter = 0;
-    let mut prefix: Vec<String> = Vec::new();
-    let mut numbers = Vec::new();
-    let mut start_num = String::from("");
-    let mut found_range = false;
-
-    for (i, c) in pattern.chars().enumerate() {
-        if c.is_alphabetic() && nest_counter == 0 {
-            prefix.push(c.to_string());
+    for segments in elements {
+        hostnames.extend(expand_segments(&segments)?);
+    }
+    hostnames.sort();
+    hostnames.dedup();
+    Ok(hostnames)
+}
+
+/// Expand a single pattern into a `BTreeSet`, giving natural ordering and
+/// dedup for free before set operations are applied.
+fn expand_set(pattern: &str) -> Result<BTreeSet<String>> {
+    Ok(expand_hostnames(pattern)?.into_iter().collect())
+}
+
+/// Union the expansions of several patterns, then apply an optional
+/// exclude and/or intersect pattern, e.g. `sxp 'n[01-10]' --exclude
+/// 'n[03-05]'` yields every host in `n[01-10]` except `n03`, `n04`, `n05`.
+fn resolve_hostnames(
+    patterns: &[String],
+    exclude: Option<&str>,
+    intersect: Option<&str>,
+) -> Result<Vec<String>> {
+    let mut hostnames = BTreeSet::new();
+    for pattern in patterns {
+        hostnames.extend(expand_set(pattern)?);
+    }
+    if let Some(exclude) = exclude {
+        let excluded = expand_set(exclude)?;
+        hostnames = hostnames.difference(&excluded).cloned().collect();
+    }
+    if let Some(intersect) = intersect {
+        let intersected = expand_set(intersect)?;
+        hostnames = hostnames.intersection(&intersected).cloned().collect();
+    }
+    Ok(hostnames.into_iter().collect())
+}
+
+/// Split a hostname around its last digit run: the prefix before it (which
+/// may itself contain digits, e.g. `rack1node` in `rack1node0`), the digit
+/// run (kept as a string to preserve zero-padding), and the trailing suffix.
+/// Returns `None` if the hostname has no digit run at all.
+fn split_host(host: &str) -> Option<(String, String, String)> {
+    let chars: Vec<char> = host.chars().collect();
+    let mut suffix_start = chars.len();
+    while suffix_start > 0 && !chars[suffix_start - 1].is_ascii_digit() {
+        suffix_start -= 1;
+    }
+    let mut digits_start = suffix_start;
+    while digits_start > 0 && chars[digits_start - 1].is_ascii_digit() {
+        digits_start -= 1;
+    }
+    if digits_start == suffix_start {
+        return None;
+    }
+    let prefix: String = chars[..digits_start].iter().collect();
+    let digits: String = chars[digits_start..suffix_start].iter().collect();
+    let suffix: String = chars[suffix_start..].iter().collect();
+    Some((prefix, digits, suffix))
+}
+
+/// Render one bucket's sorted, deduped numbers as either a bare hostname
+/// (single value, no range) or a bracketed, comma-joined list of
+/// `start-end` ranges and lone values.
+fn format_bucket(prefix: &str, suffix: &str, width: usize, numbers: &[i32]) -> String {
+    let mut runs = Vec::new();
+    let mut i = 0;
+    while i < numbers.len() {
+        let start = numbers[i];
+        let mut end = start;
+        let mut j = i + 1;
+        while j < numbers.len() && numbers[j] == end + 1 {
+            end = numbers[j];
+            j += 1;
         }
-        if c.is_numeric() {
-            numbers.push(c.to_string());
+        if end > start {
+            runs.push(format!(
+                "{}-{}",
+                pad_number(start, width as i32),
+                pad_number(end, width as i32)
+            ));
+        } else {
+            runs.push(pad_number(start, width as i32));
         }
-        if c == '[' {
-            nest_counter += 1;
-            if nest_counter > 1 {
-                bail!("Cannot nest brackets in pattern")
+        i = j;
+    }
+    if runs.len() == 1 && !runs[0].contains('-') {
+        format!("{}{}{}", prefix, runs[0], suffix)
+    } else {
+        format!("{}[{}]{}", prefix, runs.join(","), suffix)
+    }
+}
+
+/// Fold a list of hostnames into a compact SLURM-style hostlist expression,
+/// the inverse of `expand_hostnames`.
+/// # Arguments
+/// * `hostnames` - Hostnames to collapse
+/// # Returns
+/// * A single comma-separated hostlist expression
+///
+/// Example:
+/// ["n01", "n02", "n03", "n05"]
+/// ==
+/// n[01-03,05]
+///
+fn fold_hostnames(hostnames: &[String]) -> Result<String> {
+    let mut buckets: BTreeMap<(String, String, usize), Vec<i32>> = BTreeMap::new();
+    let mut passthrough: Vec<String> = Vec::new();
+
+    for host in hostnames {
+        match split_host(host) {
+            Some((prefix, digits, suffix)) => {
+                let num = digits
+                    .parse::<i32>()
+                    .map_err(|_| anyhow::anyhow!("Invalid number '{}' in hostname", digits))?;
+                buckets
+                    .entry((prefix, suffix, digits.len()))
+                    .or_default()
+                    .push(num);
             }
+            None => passthrough.push(host.clone()),
         }
-        if c == ']' {
-            if found_range {
-                let mut expanded_range =
-                    get_host_range(&prefix.join(""), &start_num, &numbers.join(""))?;
-                queue.append(&mut expanded_range);
-            }
-            nest_counter -= 1;
-            start_num = String::from("");
-            found_range = false;
+    }
+
+    let mut entries: Vec<(String, String)> = Vec::new();
+    for ((prefix, suffix, width), mut numbers) in buckets {
+        numbers.sort();
+        numbers.dedup();
+        let rendered = format_bucket(&prefix, &suffix, width, &numbers);
+        entries.push((prefix, rendered));
+    }
+    for host in passthrough {
+        entries.push((host.clone(), host));
+    }
+
+    entries.sort();
+    Ok(entries
+        .into_iter()
+        .map(|(_, rendered)| rendered)
+        .collect::<Vec<_>>()
+        .join(","))
+}
+
+/// Field names accepted inside `{...}` interpolation tokens in an
+/// expression. The bare `{}` is handled as an alias for `{host}`.
+const INTERPOLATION_FIELDS: &[&str] = &["host", "prefix", "num", "n"];
+
+/// One piece of a tokenized expression: literal text to carry through
+/// verbatim, or a `{...}` field to substitute per hostname.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ExprToken {
+    Literal(String),
+    Field(String),
+}
+
+/// Tokenize `expr` into literal runs and `{...}` field tokens, erroring on
+/// an unclosed `{` or a field name that isn't recognized. This is the
+/// only place that scans for `{...}` tokens; `interpolate` consumes the
+/// tokens this produces instead of re-scanning the expression per host.
+fn tokenize_expression(expr: &str) -> Result<Vec<ExprToken>> {
+    let mut tokens = Vec::new();
+    let mut literal = String::new();
+    let mut chars = expr.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '{' {
+            literal.push(c);
+            continue;
         }
-        if c == '-' {
-            start_num = numbers.join("");
-            found_range = true;
-            numbers.clear();
+        if !literal.is_empty() {
+            tokens.push(ExprToken::Literal(std::mem::take(&mut literal)));
         }
-        if c == ',' || i == pattern.len() - 1 {
-            if found_range {
-                let mut expanded_range =
-                    get_host_range(&prefix.join(""), &start_num, &numbers.join(""))?;
-                queue.append(&mut expanded_range);
-            }
-            start_num = String::from("");
-            let hostname = prefix.join("") + numbers.join("").as_str();
-            queue.push(hostname);
-            hostnames.append(&mut queue);
-            queue.clear();
-            numbers.clear();
-            found_range = false;
-            if nest_counter == 0 {
-                prefix.clear();
+        let mut field = String::new();
+        let mut closed = false;
+        for c in chars.by_ref() {
+            if c == '}' {
+                closed = true;
+                break;
             }
+            field.push(c);
+        }
+        if !closed {
+            bail!("Unclosed '{{' in expression '{}'", expr);
         }
+        if !field.is_empty() && !INTERPOLATION_FIELDS.contains(&field.as_str()) {
+            bail!("Unknown interpolation field '{{{}}}' in expression", field);
+        }
+        tokens.push(ExprToken::Field(field));
     }
-    hostnames.append(&mut queue);
-    hostnames.sort();
-    hostnames.dedup();
-    Ok(hostnames)
+    if !literal.is_empty() {
+        tokens.push(ExprToken::Literal(literal));
+    }
+    Ok(tokens)
+}
+
+/// Render a tokenized expression against `hostname`. `{}` and `{host}`
+/// are aliases for the full hostname; `{prefix}` is the text before the
+/// hostname's last digit run, `{num}` is that digit run as typed
+/// (zero-padding intact), and `{n}` is that same number with the padding
+/// stripped.
+fn interpolate(tokens: &[ExprToken], hostname: &str) -> String {
+    let (prefix, num, _suffix) = split_host(hostname)
+        .unwrap_or_else(|| (hostname.to_string(), String::new(), String::new()));
+    let n = if num.is_empty() {
+        String::new()
+    } else {
+        num.parse::<i32>().unwrap_or(0).to_string()
+    };
+
+    let mut out = String::new();
+    for token in tokens {
+        match token {
+            ExprToken::Literal(s) => out.push_str(s),
+            ExprToken::Field(field) => out.push_str(match field.as_str() {
+                "" | "host" => hostname,
+                "prefix" => &prefix,
+                "num" => &num,
+                "n" => &n,
+                _ => unreachable!("tokenize_expression already validated field names"),
+            }),
+        }
+    }
+    out
 }
 
-/// Returns a single string that's delimited by the separator, where
-/// each component is the expression that's interpolated by the hostname
-/// at each pattern of '{}'
+/// Returns a single string that's delimited by the separator, where each
+/// component is the expression interpolated against a hostname. `{}` (and
+/// its alias `{host}`) substitutes the full hostname, as before; `{prefix}`,
+/// `{num}`, and `{n}` substitute the hostname's parsed components.
 fn expand_pattern(hostnames: Vec<String>, expression: &str, separator: &str) -> Result<String> {
-    let expr = match expression {
-        "" => "{}",
-        expression if expression.contains("{}") => expression,
-        _ => bail!(
-            "If pattern is used, it must contain at least one instance of '{{}}' for interpolation"
-        ),
+    let expr = if expression.is_empty() {
+        "{}"
+    } else {
+        expression
     };
-    let mut expanded = Vec::new();
+    let tokens = tokenize_expression(expr)?;
+    if !tokens.iter().any(|t| matches!(t, ExprToken::Field(_))) {
+        bail!(
+            "If pattern is used, it must contain at least one instance of '{{}}', '{{host}}', '{{prefix}}', '{{num}}', or '{{n}}' for interpolation"
+        );
+    }
     let sep = match separator {
         "\\n" => "\n",
         _ => separator,
     };
-    for hostname in hostnames {
-        expanded.push(expr.replace("{}", &hostname));
-    }
+    let expanded: Vec<String> = hostnames
+        .iter()
+        .map(|hostname| interpolate(&tokens, hostname))
+        .collect();
     Ok(expanded.join(&sep))
 }
 
 fn main() -> Result<()> {
     let args = Args::parse();
 
-    let hostnames = match expand_hostnames(&args.pattern) {
+    if args.fold {
+        let input = match &args.pattern {
+            Some(pattern) => pattern.clone(),
+            None => {
+                let mut buf = String::new();
+                io::stdin().read_to_string(&mut buf)?;
+                buf
+            }
+        };
+        let hostnames: Vec<String> = input
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(str::to_string)
+            .collect();
+        let folded = match fold_hostnames(&hostnames) {
+            Ok(folded) => folded,
+            Err(e) => {
+                println!("Error: {}", e);
+                exit(1)
+            }
+        };
+        println!("{}", folded);
+        return Ok(());
+    }
+
+    let mut patterns: Vec<String> = args.pattern.into_iter().collect();
+    patterns.extend(args.extra_patterns);
+    if patterns.is_empty() {
+        bail!("the following required arguments were not provided:\n  <PATTERN>");
+    }
+
+    let hostnames = match resolve_hostnames(
+        &patterns,
+        args.exclude.as_deref(),
+        args.intersect.as_deref(),
+    ) {
         Ok(hostnames) => hostnames,
         Err(e) => {
             println!("Error: {}", e);
@@ -243,6 +521,90 @@ mod tests {
         assert!(res.is_err())
     }
 
+    #[test]
+    fn test_expand_hostnames_empty() {
+        // expand_hostnames("") -> [] (no phantom empty-string hostname)
+        assert_eq!(expand_hostnames("").unwrap(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_expand_hostnames_multi_group() {
+        // expand_hostnames("rack[1-2]node[0-1]") -> Cartesian product
+        assert_eq!(
+            expand_hostnames("rack[1-2]node[0-1]").unwrap(),
+            ["rack1node0", "rack1node1", "rack2node0", "rack2node1"]
+        );
+
+        // trailing literals after the last bracket group are preserved
+        assert_eq!(
+            expand_hostnames("n[01-03]-ib").unwrap(),
+            ["n01-ib", "n02-ib", "n03-ib"]
+        );
+
+        // top-level comma-splitting still works alongside multi-group patterns
+        assert_eq!(expand_hostnames("a[1-2],b3").unwrap(), ["a1", "a2", "b3"]);
+    }
+
+    #[test]
+    fn test_resolve_hostnames() {
+        // multiple positional patterns union together
+        assert_eq!(
+            resolve_hostnames(&["n[01-02]".to_string(), "n05".to_string()], None, None).unwrap(),
+            ["n01", "n02", "n05"]
+        );
+
+        // --exclude subtracts from the union
+        assert_eq!(
+            resolve_hostnames(&["n[01-10]".to_string()], Some("n[03-05]"), None).unwrap(),
+            ["n01", "n02", "n06", "n07", "n08", "n09", "n10"]
+        );
+
+        // --intersect keeps only hosts present in both
+        assert_eq!(
+            resolve_hostnames(&["n[01-05]".to_string()], None, Some("n[03-07]")).unwrap(),
+            ["n03", "n04", "n05"]
+        );
+    }
+
+    #[test]
+    fn test_split_host() {
+        assert_eq!(
+            split_host("n01"),
+            Some(("n".to_string(), "01".to_string(), "".to_string()))
+        );
+        assert_eq!(
+            split_host("n01-ib"),
+            Some(("n".to_string(), "01".to_string(), "-ib".to_string()))
+        );
+        assert_eq!(split_host("login"), None);
+    }
+
+    #[test]
+    fn test_fold_hostnames() {
+        // fold_hostnames(["n01", "n02", "n03", "n05"]) -> "n[01-03,05]"
+        assert_eq!(
+            fold_hostnames(&[
+                "n01".to_string(),
+                "n02".to_string(),
+                "n03".to_string(),
+                "n05".to_string()
+            ])
+            .unwrap(),
+            "n[01-03,05]"
+        );
+
+        // a single host never gets brackets
+        assert_eq!(fold_hostnames(&["n01".to_string()]).unwrap(), "n01");
+
+        // hosts with no digit run pass through verbatim
+        assert_eq!(fold_hostnames(&["login".to_string()]).unwrap(), "login");
+
+        // fold(expand(pattern)) round-trips
+        let pattern = "n[01,02],n03,n[05-07,09]";
+        let hostnames = expand_hostnames(pattern).unwrap();
+        assert_eq!(fold_hostnames(&hostnames).unwrap(), "n[01-03,05-07,09]");
+    }
+
     #[test]
     fn test_expand_pattern() {
         // expand_pattern(["n01", "n02"], "", ",") -> "n01,n02"
@@ -255,4 +617,52 @@ mod tests {
         let res = expand_pattern(vec!["n01".to_string(), "n02".to_string()], ".", ",");
         assert!(res.is_err())
     }
+
+    #[test]
+    fn test_expand_pattern_named_fields() {
+        // {host} is an alias for {}
+        assert_eq!(
+            expand_pattern(vec!["n01".to_string()], "{host}", ",").unwrap(),
+            "n01"
+        );
+
+        // {prefix}, {num}, and {n} expose the parsed components
+        assert_eq!(
+            expand_pattern(
+                vec!["n01".to_string(), "n02".to_string(), "n03".to_string()],
+                "ssh {host} # rank {n}",
+                "\\n"
+            )
+            .unwrap(),
+            "ssh n01 # rank 1\nssh n02 # rank 2\nssh n03 # rank 3"
+        );
+        assert_eq!(
+            expand_pattern(vec!["n01".to_string()], "{prefix}-{num}", ",").unwrap(),
+            "n-01"
+        );
+
+        // an unknown field name errors clearly
+        let res = expand_pattern(vec!["n01".to_string()], "{bogus}", ",");
+        assert!(res.is_err())
+    }
+
+    #[test]
+    fn test_tokenize_expression() {
+        // literals and fields interleave in order
+        assert_eq!(
+            tokenize_expression("ssh {host} # rank {n}").unwrap(),
+            vec![
+                ExprToken::Literal("ssh ".to_string()),
+                ExprToken::Field("host".to_string()),
+                ExprToken::Literal(" # rank ".to_string()),
+                ExprToken::Field("n".to_string()),
+            ]
+        );
+
+        // an unclosed '{' errors
+        assert!(tokenize_expression("{host").is_err());
+
+        // an unknown field name errors
+        assert!(tokenize_expression("{bogus}").is_err());
+    }
 }
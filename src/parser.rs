@@ -0,0 +1,192 @@
+//! Grammar for SLURM-style hostlist patterns, built on `nom` instead of a
+//! hand-rolled character loop. Parsing produces a typed AST that the
+//! expander in `main.rs` consumes; malformed input (unbalanced brackets,
+//! nested brackets, a dangling `-` in a range) falls out of the grammar
+//! itself rather than needing ad-hoc flag checks.
+//!
+//! Grammar:
+//! ```text
+//! hostlist   = element ("," element)*
+//! element    = (literal | range-set)*
+//! range-set  = "[" range-item ("," range-item)* "]"
+//! range-item = number ("-" number)?
+//! number     = digit+
+//! literal    = (any char except '[', ']', ',')+
+//! ```
+
+use anyhow::{bail, Result};
+use nom::branch::alt;
+use nom::bytes::complete::is_not;
+use nom::character::complete::{char, digit1};
+use nom::combinator::{all_consuming, cut, map, opt};
+use nom::error::ErrorKind;
+use nom::multi::{many0, separated_list1};
+use nom::sequence::{delimited, pair, preceded};
+use nom::{Finish, IResult};
+
+/// One piece of a parsed pattern element: either literal text carried
+/// through verbatim, or a bracketed range-set whose items the expander
+/// turns into the Cartesian product of hostnames.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment {
+    Literal(String),
+    RangeSet(Vec<RangeItem>),
+}
+
+/// A single item inside a bracket group: a bare number or a `start-end`
+/// range. Numbers are kept as strings so zero-padding survives until
+/// expansion.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RangeItem {
+    Single(String),
+    Range(String, String),
+}
+
+fn range_item(input: &str) -> IResult<&str, RangeItem> {
+    map(
+        pair(digit1, opt(preceded(char('-'), digit1))),
+        |(start, end): (&str, Option<&str>)| match end {
+            Some(end) => RangeItem::Range(start.to_string(), end.to_string()),
+            None => RangeItem::Single(start.to_string()),
+        },
+    )(input)
+}
+
+fn range_set(input: &str) -> IResult<&str, Segment> {
+    map(
+        delimited(
+            char('['),
+            cut(separated_list1(char(','), range_item)),
+            cut(char(']')),
+        ),
+        Segment::RangeSet,
+    )(input)
+}
+
+fn literal(input: &str) -> IResult<&str, Segment> {
+    map(is_not("[],"), |s: &str| Segment::Literal(s.to_string()))(input)
+}
+
+fn segment(input: &str) -> IResult<&str, Segment> {
+    alt((range_set, literal))(input)
+}
+
+fn element(input: &str) -> IResult<&str, Vec<Segment>> {
+    many0(segment)(input)
+}
+
+fn hostlist(input: &str) -> IResult<&str, Vec<Vec<Segment>>> {
+    separated_list1(char(','), element)(input)
+}
+
+/// Translate a nom parse failure into an "expected ..." message tailored
+/// to this grammar, rather than nom's raw `ErrorKind` debug output. The
+/// leftover input at the failure point is usually more informative than
+/// the error code itself, since constructs like `many0`/`all_consuming`
+/// collapse most failures down to "didn't consume everything".
+fn describe_failure(code: ErrorKind, leftover: &str) -> String {
+    if leftover.starts_with('-') {
+        return "expected a digit after '-'".to_string();
+    }
+    if leftover.starts_with('[') {
+        return "expected a digit, ',', or closing ']' inside the bracket group \
+                (brackets cannot nest)"
+            .to_string();
+    }
+    if leftover.starts_with(']') {
+        return "unexpected ']' with no matching '['".to_string();
+    }
+    match code {
+        ErrorKind::Digit => "expected a digit".to_string(),
+        ErrorKind::Char => "expected ']'".to_string(),
+        _ => "expected ',' or the end of the pattern".to_string(),
+    }
+}
+
+/// Parse a SLURM-style hostlist pattern into its AST: a list of elements,
+/// each an ordered sequence of literal and bracketed range-set segments.
+/// An empty pattern has no elements at all, matching the expander's
+/// historical "no pattern in, no hostnames out" behavior.
+///
+/// On malformed input the error reports the byte offset into `pattern`
+/// where parsing broke down and what was expected there, e.g. `n[01-]`
+/// fails with "expected a digit after '-'", and `n[[1]]` fails because a
+/// bracket cannot appear where a digit or closing `]` was expected.
+pub fn parse_pattern(pattern: &str) -> Result<Vec<Vec<Segment>>> {
+    if pattern.is_empty() {
+        return Ok(Vec::new());
+    }
+    match all_consuming(hostlist)(pattern).finish() {
+        Ok((_, parsed)) => Ok(parsed),
+        Err(e) => {
+            let offset = pattern.len() - e.input.len();
+            let context: String = e.input.chars().take(10).collect();
+            bail!(
+                "invalid hostname pattern at byte {}: {} (near '{}')",
+                offset,
+                describe_failure(e.code, e.input),
+                context
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_pattern() {
+        // a plain literal element
+        assert_eq!(
+            parse_pattern("n03").unwrap(),
+            vec![vec![Segment::Literal("n03".to_string())]]
+        );
+
+        // a single range-set
+        assert_eq!(
+            parse_pattern("n[01-03]").unwrap(),
+            vec![vec![
+                Segment::Literal("n".to_string()),
+                Segment::RangeSet(vec![RangeItem::Range("01".to_string(), "03".to_string())]),
+            ]]
+        );
+
+        // multiple bracket groups and a trailing literal in one element
+        assert_eq!(
+            parse_pattern("n[1-2]-ib").unwrap(),
+            vec![vec![
+                Segment::Literal("n".to_string()),
+                Segment::RangeSet(vec![RangeItem::Range("1".to_string(), "2".to_string())]),
+                Segment::Literal("-ib".to_string()),
+            ]]
+        );
+
+        // top-level commas split into separate elements
+        assert_eq!(
+            parse_pattern("n01,n02").unwrap(),
+            vec![
+                vec![Segment::Literal("n01".to_string())],
+                vec![Segment::Literal("n02".to_string())],
+            ]
+        );
+
+        // an empty pattern has no elements at all, not one phantom
+        // empty-string element
+        assert_eq!(parse_pattern("").unwrap(), Vec::<Vec<Segment>>::new());
+    }
+
+    #[test]
+    fn test_parse_pattern_errors() {
+        // a dangling '-' with no trailing number is rejected, and the
+        // error names what was expected
+        let err = parse_pattern("n[01-]").unwrap_err().to_string();
+        assert!(err.contains("expected a digit after '-'"), "{}", err);
+
+        // nested brackets are rejected
+        assert!(parse_pattern("n[[1]]").is_err());
+
+        // an unbalanced (unclosed) bracket is rejected
+        assert!(parse_pattern("n[01-03").is_err());
+    }
+}
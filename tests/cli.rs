@@ -0,0 +1,13 @@
+use std::process::Command;
+
+#[test]
+fn test_cli_custom_expression_and_separator_with_multiple_patterns() {
+    // a custom EXPRESSION/SEPARATOR must still work alongside the
+    // repeatable -p/--pattern flag for unioning extra patterns
+    let output = Command::new(env!("CARGO_BIN_EXE_sxp"))
+        .args(["n01", "-p", "n02", "{}", "|"])
+        .output()
+        .expect("failed to run sxp binary");
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap().trim(), "n01|n02");
+}